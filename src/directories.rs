@@ -1,6 +1,11 @@
-use std::{collections::VecDeque, io::Write, path::PathBuf};
+use std::{
+    collections::{HashSet, VecDeque},
+    path::PathBuf,
+};
 
 use crate::{
+    fs::{EntryKind, Fs},
+    ignore::IgnoreSet,
     template::{ReplacementCount, Template},
     Error, ProgramArgs,
 };
@@ -13,7 +18,6 @@ pub enum FileOp {
     Parse(FromDir, ToDir),
     #[allow(unused)]
     Skip(FromDir),
-    #[allow(unused)]
     Simlink(FromDir, ToDir),
 }
 
@@ -22,23 +26,33 @@ impl FileOp {
         self,
         args: &ProgramArgs,
     ) -> Result<(ReplacementCount, FromDir, Option<ToDir>), (FromDir, Error)> {
+        let fs = args.fs.as_ref();
         match self {
             FileOp::Parse(fin, fout) => fin
-                .parse_into(fout.clone(), &args.open, &args.close, &args.replacements)
+                .parse_into(
+                    fs,
+                    fout.clone(),
+                    &args.open,
+                    &args.close,
+                    &args.replacements,
+                    args.sync,
+                    args.strict,
+                )
                 .or_else(|e| match e {
                     Error::FileReadError => {
                         eprintln!("Cannot read file {fin:?}, trying copy");
-                        fin.copy_into(fout.clone())
-                            .map(|_| 0)
+                        fin.copy_into(fs, fout.clone(), args.sync)
+                            .map(|_| ReplacementCount::default())
                             .map_err(|_| (fin.clone(), Error::FileCopyError))
                     }
                     _ => Err((fin.clone(), e)),
                 })
                 .map(|replacements| (replacements, fin, Some(fout))),
-            FileOp::Simlink(_, _) => {
-                unimplemented!()
-            }
-            FileOp::Skip(fin) => Ok((0, fin, None)),
+            FileOp::Simlink(fin, fout) => fin
+                .symlink_into(fs, fout.clone(), args.sync)
+                .map(|_| (ReplacementCount::default(), fin.clone(), Some(fout)))
+                .map_err(|e| (fin.clone(), e)),
+            FileOp::Skip(fin) => Ok((ReplacementCount::default(), fin, None)),
         }
     }
 }
@@ -59,7 +73,12 @@ impl DirectoryFiles {
         self.files.len()
     }
 
-    pub fn child_files_recursive<P: Into<PathBuf> + Clone>(from: P, to: P) -> Result<Self, Error> {
+    pub fn child_files_recursive<P: Into<PathBuf> + Clone>(
+        fs: &dyn Fs,
+        excludes: &IgnoreSet,
+        from: P,
+        to: P,
+    ) -> Result<Self, Error> {
         let mut obj = Self {
             files: VecDeque::new(),
         };
@@ -71,33 +90,28 @@ impl DirectoryFiles {
 
         while queue.len() > 0 {
             let next_dir_path = queue.pop_front().unwrap();
-            let Ok(dir) = std::fs::read_dir(&next_dir_path) else {
+            let Ok(dir) = fs.read_dir(&next_dir_path) else {
                 obj.files.push_back(FileOp::Skip(next_dir_path.clone()));
                 continue;
             };
-            for file in dir {
-                let Ok(file) = file else {
+            for entry in dir {
+                let path = entry.path;
+                let base_rel = path.strip_prefix(&base).unwrap();
+
+                let is_dir = entry.kind == EntryKind::Dir;
+                if excludes.is_excluded(&base_rel.to_string_lossy(), is_dir) {
                     continue;
-                };
+                }
 
-                let path = file.path();
-                let base = path.strip_prefix(&base).unwrap();
                 let mut new_path = repl.clone().into_os_string();
                 new_path.push("/");
-                new_path.push(base);
+                new_path.push(base_rel);
                 let out_dir: PathBuf = new_path.into();
 
-                let Ok(ft) = file.file_type() else {
-                    obj.files.push_back(FileOp::Skip(path));
-                    continue;
-                };
-
-                if ft.is_dir() {
-                    queue.push_back(file.path());
-                } else if ft.is_file() {
-                    obj.files.push_back(FileOp::Parse(path, out_dir));
-                } else {
-                    obj.files.push_back(FileOp::Simlink(path, out_dir));
+                match entry.kind {
+                    EntryKind::Dir => queue.push_back(path),
+                    EntryKind::File => obj.files.push_back(FileOp::Parse(path, out_dir)),
+                    EntryKind::Symlink => obj.files.push_back(FileOp::Simlink(path, out_dir)),
                 }
             }
         }
@@ -106,55 +120,174 @@ impl DirectoryFiles {
 }
 
 pub trait FileOps {
-    fn write_into_ensure_dirs(&self, data: &[u8], into: PathBuf) -> Result<(), Error>;
-    fn copy_into(&self, into: PathBuf) -> Result<(), Error>;
+    fn write_into_ensure_dirs(
+        &self,
+        fs: &dyn Fs,
+        data: &[u8],
+        into: PathBuf,
+        sync: bool,
+    ) -> Result<(), Error>;
+    fn copy_into(&self, fs: &dyn Fs, into: PathBuf, sync: bool) -> Result<(), Error>;
+    fn symlink_into(&self, fs: &dyn Fs, into: PathBuf, sync: bool) -> Result<(), Error>;
     fn parse_into(
         &self,
+        fs: &dyn Fs,
         into: PathBuf,
         od: &str,
         cd: &str,
         replacements: &yaml_rust::Yaml,
+        sync: bool,
+        strict: bool,
     ) -> Result<ReplacementCount, Error>;
 }
 
+/// Builds a sibling `.name.tmpNNN` path in `dir` to stage a write to `into`
+/// before the atomic rename.
+fn tmp_sibling_path(dir: &std::path::Path, into: &std::path::Path) -> PathBuf {
+    let tmp_name = format!(
+        ".{}.tmp{}",
+        into.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("tempa"),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default()
+    );
+    dir.join(tmp_name)
+}
+
 impl FileOps for PathBuf {
-    fn write_into_ensure_dirs(&self, data: &[u8], into: PathBuf) -> Result<(), Error> {
+    fn write_into_ensure_dirs(
+        &self,
+        fs: &dyn Fs,
+        data: &[u8],
+        into: PathBuf,
+        sync: bool,
+    ) -> Result<(), Error> {
+        if sync && fs.read(&into).map(|existing| existing == data).unwrap_or(false) {
+            return Ok(());
+        }
+
         let dir = into.parent().unwrap().to_path_buf();
-        std::fs::create_dir_all(dir).map_err(|_| Error::DirectoryCreateError)?;
-
-        let mut file = std::fs::File::options()
-            .read(true)
-            .write(true)
-            .create_new(true)
-            .open(into)
-            .map_err(|_| Error::FileCreateError)?;
-        file.write_all(data).map_err(|_| Error::FileWriteError)?;
-        Ok(())
+        fs.create_dir_all(&dir).map_err(|_| Error::DirectoryCreateError)?;
+
+        let tmp_path = tmp_sibling_path(&dir, &into);
+
+        if fs.write(&tmp_path, data).is_err() {
+            let _ = fs.remove_file(&tmp_path);
+            return Err(Error::FileWriteError);
+        }
+
+        fs.rename(&tmp_path, &into).map_err(|_| {
+            let _ = fs.remove_file(&tmp_path);
+            Error::FileRenameError
+        })
     }
-    fn copy_into(&self, into: PathBuf) -> Result<(), Error> {
+    fn copy_into(&self, fs: &dyn Fs, into: PathBuf, sync: bool) -> Result<(), Error> {
+        if sync {
+            if let (Ok(src), Ok(dst)) = (fs.read(self), fs.read(&into)) {
+                if src == dst {
+                    return Ok(());
+                }
+            }
+        }
+
         let dir = into.parent().unwrap().to_path_buf();
-        std::fs::create_dir_all(dir).map_err(|_| Error::DirectoryCreateError)?;
-        std::fs::copy(self, into).map_err(|_| Error::FileCopyError)?;
+        fs.create_dir_all(&dir).map_err(|_| Error::DirectoryCreateError)?;
+        fs.copy(self, &into).map_err(|_| Error::FileCopyError)?;
         Ok(())
     }
+    fn symlink_into(&self, fs: &dyn Fs, into: PathBuf, sync: bool) -> Result<(), Error> {
+        let target = fs.read_link(self).map_err(|_| Error::FileReadError)?;
+
+        if sync && fs.read_link(&into).map(|existing| existing == target).unwrap_or(false) {
+            return Ok(());
+        }
+
+        let dir = into.parent().unwrap().to_path_buf();
+        fs.create_dir_all(&dir).map_err(|_| Error::DirectoryCreateError)?;
+
+        let tmp_path = tmp_sibling_path(&dir, &into);
+
+        if fs.symlink(&target, &tmp_path).is_err() {
+            let _ = fs.remove_file(&tmp_path);
+            return Err(Error::FileCreateError);
+        }
+
+        fs.rename(&tmp_path, &into).map_err(|_| {
+            let _ = fs.remove_file(&tmp_path);
+            Error::FileRenameError
+        })
+    }
     fn parse_into(
         &self,
+        fs: &dyn Fs,
         into: PathBuf,
         od: &str,
         cd: &str,
         replacements: &yaml_rust::Yaml,
+        sync: bool,
+        strict: bool,
     ) -> Result<ReplacementCount, Error> {
-        let file = std::fs::read_to_string(self).map_err(|_| Error::FileReadError)?;
+        let file = fs.read_to_string(self).map_err(|_| Error::FileReadError)?;
         let template = Template::from_str(&file, od, cd);
-        let (replacements, new_file) = template.apply(&replacements);
-        self.write_into_ensure_dirs(new_file.as_bytes(), into)
+        let (replacements, new_file) =
+            template.apply(replacements, strict, &self.to_string_lossy())?;
+        self.write_into_ensure_dirs(fs, new_file.as_bytes(), into, sync)
             .map(|_| replacements)
     }
 }
 
+const MANIFEST_FILE_NAME: &str = ".tempa-manifest";
+
+fn manifest_path(output_root: &PathBuf) -> PathBuf {
+    output_root.join(MANIFEST_FILE_NAME)
+}
+
+/// Loads the set of outputs tempa wrote on the last run against this output
+/// directory, so `--sync` can tell its own outputs apart from files a user
+/// or another tool placed there. Missing or unreadable manifests are treated
+/// as "nothing tracked yet", never as "prune everything".
+pub fn load_manifest(fs: &dyn Fs, output_root: &PathBuf) -> HashSet<PathBuf> {
+    fs.read_to_string(&manifest_path(output_root))
+        .map(|contents| contents.lines().map(PathBuf::from).collect())
+        .unwrap_or_default()
+}
+
+/// Persists `outputs` as the manifest for this output directory, so the next
+/// `--sync` run knows which files it's allowed to prune.
+pub fn write_manifest(fs: &dyn Fs, output_root: &PathBuf, outputs: &HashSet<PathBuf>) {
+    let mut lines: Vec<String> = outputs
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    lines.sort();
+
+    if let Err(e) = fs.write(&manifest_path(output_root), lines.join("\n").as_bytes()) {
+        eprintln!("Error writing output manifest: {e}");
+    }
+}
+
+/// Removes outputs tempa authored on a previous `--sync` run whose source no
+/// longer exists, so the output tree converges on `expected` without a full
+/// wipe. Only ever deletes paths recorded in `previous` — files a user or
+/// another tool dropped into the output tree are never touched, even if
+/// they're not in `expected`.
+pub fn prune_stale_outputs(fs: &dyn Fs, previous: &HashSet<PathBuf>, expected: &HashSet<PathBuf>) {
+    for path in previous.difference(expected) {
+        match fs.remove_file(path) {
+            Ok(_) => println!("Removed stale output {path:?}"),
+            Err(e) => eprintln!("Error removing stale output {path:?}: {e}"),
+        }
+    }
+}
+
 #[test]
 fn read_file_list() {
-    let files = DirectoryFiles::child_files_recursive("./test", "./out").unwrap();
+    let fs = crate::fs::RealFs;
+    let excludes = IgnoreSet::new();
+    let files = DirectoryFiles::child_files_recursive(&fs, &excludes, "./test", "./out").unwrap();
 
     for file in files {
         println!("{file:?}");
@@ -0,0 +1,426 @@
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+#[derive(Debug, Clone)]
+pub struct DirEntryInfo {
+    pub path: PathBuf,
+    pub kind: EntryKind,
+}
+
+/// Abstracts the filesystem calls `FileOp` needs, so a run can be previewed
+/// with `--dry-run` or exercised in tests without touching disk.
+pub trait Fs: Send + Sync {
+    fn read_to_string(&self, path: &Path) -> std::io::Result<String>;
+    fn read(&self, path: &Path) -> std::io::Result<Vec<u8>>;
+    fn write(&self, path: &Path, data: &[u8]) -> std::io::Result<()>;
+    fn create_dir_all(&self, path: &Path) -> std::io::Result<()>;
+    fn copy(&self, from: &Path, to: &Path) -> std::io::Result<u64>;
+    fn read_dir(&self, path: &Path) -> std::io::Result<Vec<DirEntryInfo>>;
+    fn read_link(&self, path: &Path) -> std::io::Result<PathBuf>;
+    fn symlink(&self, target: &Path, link: &Path) -> std::io::Result<()>;
+    fn remove_dir_all(&self, path: &Path) -> std::io::Result<()>;
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()>;
+    fn remove_file(&self, path: &Path) -> std::io::Result<()>;
+}
+
+pub struct RealFs;
+
+impl Fs for RealFs {
+    fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        std::fs::write(path, data)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(path)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> std::io::Result<u64> {
+        std::fs::copy(from, to)
+    }
+
+    fn read_dir(&self, path: &Path) -> std::io::Result<Vec<DirEntryInfo>> {
+        std::fs::read_dir(path)?
+            .map(|entry| {
+                let entry = entry?;
+                let file_type = entry.file_type()?;
+                let kind = if file_type.is_dir() {
+                    EntryKind::Dir
+                } else if file_type.is_file() {
+                    EntryKind::File
+                } else {
+                    EntryKind::Symlink
+                };
+                Ok(DirEntryInfo {
+                    path: entry.path(),
+                    kind,
+                })
+            })
+            .collect()
+    }
+
+    fn read_link(&self, path: &Path) -> std::io::Result<PathBuf> {
+        std::fs::read_link(path)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> std::io::Result<()> {
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(target, link)
+        }
+        #[cfg(windows)]
+        {
+            if target.is_dir() {
+                std::os::windows::fs::symlink_dir(target, link)
+            } else {
+                std::os::windows::fs::symlink_file(target, link)
+            }
+        }
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::remove_dir_all(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::remove_file(path)
+    }
+}
+
+/// Reads the real filesystem like `RealFs`, but turns every write/copy/remove
+/// into a logged no-op so a run can be previewed with `--dry-run`.
+pub struct DryRunFs {
+    inner: RealFs,
+}
+
+impl DryRunFs {
+    pub fn new() -> Self {
+        Self { inner: RealFs }
+    }
+}
+
+impl Default for DryRunFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fs for DryRunFs {
+    fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+        self.inner.read_to_string(path)
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        self.inner.read(path)
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        println!("[dry-run] would write {} bytes to {path:?}", data.len());
+        Ok(())
+    }
+
+    fn create_dir_all(&self, _path: &Path) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> std::io::Result<u64> {
+        println!("[dry-run] would copy {from:?} to {to:?}");
+        Ok(0)
+    }
+
+    fn read_dir(&self, path: &Path) -> std::io::Result<Vec<DirEntryInfo>> {
+        self.inner.read_dir(path)
+    }
+
+    fn read_link(&self, path: &Path) -> std::io::Result<PathBuf> {
+        self.inner.read_link(path)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> std::io::Result<()> {
+        println!("[dry-run] would symlink {link:?} -> {target:?}");
+        Ok(())
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        println!("[dry-run] would remove directory {path:?}");
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        println!("[dry-run] would replace {to:?} with {from:?}");
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> std::io::Result<()> {
+        println!("[dry-run] would remove {path:?}");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::directories::FileOp;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Clone)]
+    enum FakeEntry {
+        File(Vec<u8>),
+        Dir,
+        Symlink(PathBuf),
+    }
+
+    /// In-memory stand-in for [`Fs`], so `FileOp::execute` can be exercised
+    /// without touching disk.
+    pub struct FakeFs {
+        entries: Mutex<HashMap<PathBuf, FakeEntry>>,
+    }
+
+    impl FakeFs {
+        pub fn new() -> Self {
+            Self {
+                entries: Mutex::new(HashMap::new()),
+            }
+        }
+
+        pub fn with_file(self, path: impl Into<PathBuf>, contents: impl Into<Vec<u8>>) -> Self {
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(path.into(), FakeEntry::File(contents.into()));
+            self
+        }
+
+        pub fn with_symlink(self, path: impl Into<PathBuf>, target: impl Into<PathBuf>) -> Self {
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(path.into(), FakeEntry::Symlink(target.into()));
+            self
+        }
+    }
+
+    fn not_found() -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "no such entry")
+    }
+
+    impl Fs for FakeFs {
+        fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+            match self.entries.lock().unwrap().get(path) {
+                Some(FakeEntry::File(data)) => String::from_utf8(data.clone())
+                    .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "not utf8")),
+                _ => Err(not_found()),
+            }
+        }
+
+        fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+            match self.entries.lock().unwrap().get(path) {
+                Some(FakeEntry::File(data)) => Ok(data.clone()),
+                _ => Err(not_found()),
+            }
+        }
+
+        fn write(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(path.to_path_buf(), FakeEntry::File(data.to_vec()));
+            Ok(())
+        }
+
+        fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+            self.entries
+                .lock()
+                .unwrap()
+                .entry(path.to_path_buf())
+                .or_insert(FakeEntry::Dir);
+            Ok(())
+        }
+
+        fn copy(&self, from: &Path, to: &Path) -> std::io::Result<u64> {
+            let data = match self.entries.lock().unwrap().get(from) {
+                Some(FakeEntry::File(data)) => data.clone(),
+                _ => return Err(not_found()),
+            };
+            let len = data.len() as u64;
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(to.to_path_buf(), FakeEntry::File(data));
+            Ok(len)
+        }
+
+        fn read_dir(&self, path: &Path) -> std::io::Result<Vec<DirEntryInfo>> {
+            let entries = self.entries.lock().unwrap();
+            Ok(entries
+                .iter()
+                .filter(|(p, _)| p.parent() == Some(path))
+                .map(|(p, entry)| DirEntryInfo {
+                    path: p.clone(),
+                    kind: match entry {
+                        FakeEntry::File(_) => EntryKind::File,
+                        FakeEntry::Dir => EntryKind::Dir,
+                        FakeEntry::Symlink(_) => EntryKind::Symlink,
+                    },
+                })
+                .collect())
+        }
+
+        fn read_link(&self, path: &Path) -> std::io::Result<PathBuf> {
+            match self.entries.lock().unwrap().get(path) {
+                Some(FakeEntry::Symlink(target)) => Ok(target.clone()),
+                _ => Err(not_found()),
+            }
+        }
+
+        fn symlink(&self, target: &Path, link: &Path) -> std::io::Result<()> {
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(link.to_path_buf(), FakeEntry::Symlink(target.to_path_buf()));
+            Ok(())
+        }
+
+        fn remove_dir_all(&self, path: &Path) -> std::io::Result<()> {
+            self.entries.lock().unwrap().retain(|p, _| !p.starts_with(path));
+            Ok(())
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+            let entry = self.entries.lock().unwrap().remove(from).ok_or_else(not_found)?;
+            self.entries.lock().unwrap().insert(to.to_path_buf(), entry);
+            Ok(())
+        }
+
+        fn remove_file(&self, path: &Path) -> std::io::Result<()> {
+            self.entries.lock().unwrap().remove(path);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn fake_fs_write_then_read_round_trips() {
+        let fs = FakeFs::new();
+        fs.write(Path::new("/out/a.txt"), b"hello").unwrap();
+        assert_eq!(fs.read_to_string(Path::new("/out/a.txt")).unwrap(), "hello");
+    }
+
+    #[test]
+    fn fake_fs_rename_moves_entry() {
+        let fs = FakeFs::new().with_file("/out/a.tmp", "hello");
+        fs.rename(Path::new("/out/a.tmp"), Path::new("/out/a.txt")).unwrap();
+        assert!(fs.read_to_string(Path::new("/out/a.tmp")).is_err());
+        assert_eq!(fs.read_to_string(Path::new("/out/a.txt")).unwrap(), "hello");
+    }
+
+    /// Builds a minimal `ProgramArgs` so `FileOp::execute` can be driven
+    /// against a `FakeFs` without touching disk or the CLI parser.
+    fn program_args(fs: Arc<dyn Fs>, replacements_yaml: &str, sync: bool) -> crate::ProgramArgs {
+        let replacements = yaml_rust::YamlLoader::load_from_str(replacements_yaml).unwrap()[0].clone();
+        crate::ProgramArgs {
+            input: "/in".to_string(),
+            output: "/out".to_string(),
+            open: "%%".to_string(),
+            close: "%%".to_string(),
+            replacements,
+            fs,
+            excludes: crate::ignore::IgnoreSet::new(),
+            sync,
+            strict: false,
+            dry_run: false,
+        }
+    }
+
+    #[test]
+    fn execute_parse_writes_through_temp_rename() {
+        let fs: Arc<dyn Fs> = Arc::new(FakeFs::new().with_file("/in/greeting.txt", "Hello %%name%%!"));
+        let args = program_args(fs.clone(), "name: World", false);
+
+        let op = FileOp::Parse(PathBuf::from("/in/greeting.txt"), PathBuf::from("/out/greeting.txt"));
+        let (replacements, from, to) = op.execute(&args).unwrap();
+
+        assert_eq!(from, PathBuf::from("/in/greeting.txt"));
+        assert_eq!(to, Some(PathBuf::from("/out/greeting.txt")));
+        assert_eq!(replacements.applied, 1);
+        assert_eq!(
+            fs.read_to_string(Path::new("/out/greeting.txt")).unwrap(),
+            "Hello World!"
+        );
+        // The temp file staged alongside the destination should not survive the rename.
+        let out_entries: Vec<_> = fs.read_dir(Path::new("/out")).unwrap();
+        assert_eq!(
+            out_entries.iter().map(|e| &e.path).collect::<Vec<_>>(),
+            vec![&PathBuf::from("/out/greeting.txt")]
+        );
+    }
+
+    #[test]
+    fn execute_parse_sync_skips_unchanged_output() {
+        let fs: Arc<dyn Fs> = Arc::new(
+            FakeFs::new()
+                .with_file("/in/a.txt", "content")
+                .with_file("/out/a.txt", "content"),
+        );
+        let args = program_args(fs.clone(), "{}", true);
+
+        let (_, _, to) = FileOp::Parse(PathBuf::from("/in/a.txt"), PathBuf::from("/out/a.txt"))
+            .execute(&args)
+            .unwrap();
+
+        assert_eq!(to, Some(PathBuf::from("/out/a.txt")));
+        assert_eq!(fs.read_to_string(Path::new("/out/a.txt")).unwrap(), "content");
+    }
+
+    #[test]
+    fn execute_symlink_creates_link_at_destination() {
+        let fs: Arc<dyn Fs> = Arc::new(FakeFs::new().with_symlink("/in/link", "../shared/target.txt"));
+        let args = program_args(fs.clone(), "{}", false);
+
+        let (_, _, to) = FileOp::Simlink(PathBuf::from("/in/link"), PathBuf::from("/out/link"))
+            .execute(&args)
+            .unwrap();
+
+        assert_eq!(to, Some(PathBuf::from("/out/link")));
+        assert_eq!(
+            fs.read_link(Path::new("/out/link")).unwrap(),
+            PathBuf::from("../shared/target.txt")
+        );
+    }
+
+    #[test]
+    fn execute_symlink_overwrites_changed_target_under_sync() {
+        let fs: Arc<dyn Fs> = Arc::new(
+            FakeFs::new()
+                .with_symlink("/in/link", "new-target.txt")
+                .with_symlink("/out/link", "old-target.txt"),
+        );
+        let args = program_args(fs.clone(), "{}", true);
+
+        FileOp::Simlink(PathBuf::from("/in/link"), PathBuf::from("/out/link"))
+            .execute(&args)
+            .unwrap();
+
+        assert_eq!(
+            fs.read_link(Path::new("/out/link")).unwrap(),
+            PathBuf::from("new-target.txt")
+        );
+    }
+}
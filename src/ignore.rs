@@ -0,0 +1,142 @@
+/// A compiled set of gitignore-style patterns, matched against paths relative
+/// to the walk root. Patterns are applied in order and later patterns
+/// override earlier ones, so a trailing `!pattern` can re-include a path an
+/// earlier pattern excluded.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreSet {
+    patterns: Vec<IgnorePattern>,
+}
+
+#[derive(Debug, Clone)]
+struct IgnorePattern {
+    segments: Vec<String>,
+    negate: bool,
+    dir_only: bool,
+}
+
+impl IgnoreSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compiles every non-empty, non-comment line of `source` and appends the
+    /// resulting patterns, in order, to this set.
+    pub fn add_patterns(&mut self, source: &str) {
+        for line in source.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.patterns.push(IgnorePattern::compile(line));
+        }
+    }
+
+    /// Whether `relative_path` (`/`-separated, relative to the walk root)
+    /// should be excluded. `is_dir` gates patterns with a trailing `/`.
+    pub fn is_excluded(&self, relative_path: &str, is_dir: bool) -> bool {
+        let segments: Vec<&str> = relative_path.split('/').collect();
+        let mut excluded = false;
+        for pattern in &self.patterns {
+            if pattern.dir_only && !is_dir {
+                continue;
+            }
+            if pattern.matches(&segments) {
+                excluded = !pattern.negate;
+            }
+        }
+        excluded
+    }
+}
+
+impl IgnorePattern {
+    fn compile(line: &str) -> Self {
+        let (negate, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let dir_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+
+        // A pattern with a slash anywhere but the end is anchored to the walk
+        // root, same as gitignore. One with no slash at all matches at any
+        // depth, so it's treated as if prefixed with `**/`.
+        let anchored = line.contains('/');
+        let line = line.trim_start_matches('/');
+
+        let segments = if anchored || line.is_empty() {
+            line.split('/').map(str::to_string).collect()
+        } else {
+            vec!["**".to_string(), line.to_string()]
+        };
+
+        Self {
+            segments,
+            negate,
+            dir_only,
+        }
+    }
+
+    fn matches(&self, path: &[&str]) -> bool {
+        match_segments(&self.segments, path)
+    }
+}
+
+fn match_segments(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((seg, rest)) if seg == "**" => {
+            if rest.is_empty() {
+                return true;
+            }
+            (0..=path.len()).any(|i| match_segments(rest, &path[i..]))
+        }
+        Some((seg, rest)) => match path.split_first() {
+            Some((p, prest)) => segment_match(seg, p) && match_segments(rest, prest),
+            None => false,
+        },
+    }
+}
+
+fn segment_match(pattern: &str, segment: &str) -> bool {
+    fn helper(p: &[u8], s: &[u8]) -> bool {
+        match p.first() {
+            None => s.is_empty(),
+            Some(b'*') => (0..=s.len()).any(|i| helper(&p[1..], &s[i..])),
+            Some(&c) => s.first() == Some(&c) && helper(&p[1..], &s[1..]),
+        }
+    }
+    helper(pattern.as_bytes(), segment.as_bytes())
+}
+
+#[test]
+fn excludes_nested_git_directory() {
+    let mut ignore = IgnoreSet::new();
+    ignore.add_patterns(".git/");
+    assert!(ignore.is_excluded(".git", true));
+    assert!(ignore.is_excluded("vendor/lib/.git", true));
+    assert!(!ignore.is_excluded(".git", false));
+}
+
+#[test]
+fn unanchored_pattern_matches_any_depth() {
+    let mut ignore = IgnoreSet::new();
+    ignore.add_patterns("*.log");
+    assert!(ignore.is_excluded("debug.log", false));
+    assert!(ignore.is_excluded("nested/dir/debug.log", false));
+}
+
+#[test]
+fn later_negation_re_includes_path() {
+    let mut ignore = IgnoreSet::new();
+    ignore.add_patterns("build/*\n!build/keep.txt");
+    assert!(ignore.is_excluded("build/output.o", false));
+    assert!(!ignore.is_excluded("build/keep.txt", false));
+}
+
+#[test]
+fn double_star_matches_across_segments() {
+    let mut ignore = IgnoreSet::new();
+    ignore.add_patterns("assets/**/*.psd");
+    assert!(ignore.is_excluded("assets/a/b/draft.psd", false));
+    assert!(!ignore.is_excluded("assets/draft.psdx", false));
+}
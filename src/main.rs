@@ -1,9 +1,13 @@
 mod directories;
+mod fs;
+mod ignore;
 mod template;
 
 use std::sync::Arc;
 
-use directories::DirectoryFiles;
+use directories::{DirectoryFiles, FileOp};
+use fs::{DryRunFs, Fs, RealFs};
+use ignore::IgnoreSet;
 use tokio::task::JoinSet;
 
 const DEBUG: bool = false;
@@ -15,6 +19,8 @@ enum Error {
     FileWriteError,
     FileCopyError,
     FileCreateError,
+    FileRenameError,
+    StrictReplacementError { file: String, key: String },
     ArgumentsNoInputError,
     ArgumentsNoOutputError,
     ArgumentsNoReplacementsError,
@@ -22,13 +28,18 @@ enum Error {
     PrepareCleanOutputError,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 struct ProgramArgs {
     input: String,
     output: String,
     open: String,
     close: String,
     replacements: yaml_rust::Yaml,
+    fs: Arc<dyn Fs>,
+    excludes: IgnoreSet,
+    sync: bool,
+    strict: bool,
+    dry_run: bool,
 }
 
 impl TryFrom<getopts::Matches> for ProgramArgs {
@@ -48,12 +59,33 @@ impl TryFrom<getopts::Matches> for ProgramArgs {
         )
         .map_err(|_| Error::ReplacementsReadError)?;
 
+        let dry_run = value.opt_present("n");
+        let fs: Arc<dyn Fs> = if dry_run {
+            Arc::new(DryRunFs::new())
+        } else {
+            Arc::new(RealFs)
+        };
+
+        let mut excludes = IgnoreSet::new();
+        let tempaignore_path = std::path::Path::new(&input).join(".tempaignore");
+        if let Ok(contents) = std::fs::read_to_string(tempaignore_path) {
+            excludes.add_patterns(&contents);
+        }
+        for pattern in value.opt_strs("x") {
+            excludes.add_patterns(&pattern);
+        }
+
         Ok(ProgramArgs {
             input,
             output,
             open,
             close,
             replacements: replacements[0].to_owned(),
+            fs,
+            excludes,
+            sync: value.opt_present("y"),
+            strict: value.opt_present("t"),
+            dry_run,
         })
     }
 }
@@ -79,12 +111,35 @@ fn setup_getopts(options: &mut getopts::Options) {
         "SEP",
     );
     options.reqopt("r", "replacements", "replacements file (yaml)", "FILE");
+    options.optmulti(
+        "x",
+        "exclude",
+        "gitignore-style glob to exclude, relative to the input directory (repeatable)",
+        "GLOB",
+    );
+    options.optflag(
+        "n",
+        "dry-run",
+        "preview the run without writing, copying or deleting anything",
+    );
+    options.optflag(
+        "y",
+        "sync",
+        "incremental mode: only rewrite changed outputs and delete outputs whose source is gone",
+    );
+    options.optflag(
+        "t",
+        "strict",
+        "error out on a replacement that resolves to no YAML string and has no inline default",
+    );
     options.optflag("h", "help", "print help menu");
 }
 
 fn prepare(args: &ProgramArgs) -> Result<(), Error> {
     println!("Cleaning output directory");
-    std::fs::remove_dir_all(&args.output).map_err(|_| Error::PrepareCleanOutputError)
+    args.fs
+        .remove_dir_all(std::path::Path::new(&args.output))
+        .map_err(|_| Error::PrepareCleanOutputError)
 }
 
 #[tokio::main]
@@ -109,10 +164,32 @@ async fn main() {
     }
 
     let args: ProgramArgs = matches.try_into().expect("Error parsing arguments.");
-    prepare(&args).expect("Error during prepare stage.");
+    let output_root = std::path::PathBuf::from(&args.output);
+    let previous_outputs = if args.sync {
+        directories::load_manifest(args.fs.as_ref(), &output_root)
+    } else {
+        Default::default()
+    };
+    if !args.sync {
+        prepare(&args).expect("Error during prepare stage.");
+    }
 
-    let files = DirectoryFiles::child_files_recursive(&args.input, &args.output)
-        .expect("Error reading input directory");
+    let files: Vec<FileOp> = DirectoryFiles::child_files_recursive(
+        args.fs.as_ref(),
+        &args.excludes,
+        &args.input,
+        &args.output,
+    )
+    .expect("Error reading input directory")
+    .collect();
+
+    let expected_outputs: std::collections::HashSet<std::path::PathBuf> = files
+        .iter()
+        .filter_map(|op| match op {
+            FileOp::Parse(_, to) | FileOp::Simlink(_, to) => Some(to.clone()),
+            FileOp::Skip(_) => None,
+        })
+        .collect();
 
     let args = Arc::new(args);
     let mut parsed_count = 0;
@@ -129,14 +206,14 @@ async fn main() {
                         }
                         return 1;
                     };
-                    if replacements > 0 && DEBUG {
-                        println!(
-                            "Parsed {from:?} into {to:?} applying {replacements} replacements."
-                        );
-                    } else if replacements > 0 {
+                    if args.dry_run || DEBUG {
+                        if replacements.total() > 0 {
+                            println!("Parsed {from:?} into {to:?} applying {replacements}.");
+                        } else {
+                            println!("Copied {from:?} into {to:?}.");
+                        }
+                    } else if replacements.total() > 0 {
                         println!("Parsed {from:?}");
-                    } else if DEBUG {
-                        println!("Copied {from:?} into {to:?}.");
                     }
                     return 1;
                 }
@@ -161,4 +238,9 @@ async fn main() {
             total_files - parsed_count
         );
     }
+
+    if args.sync {
+        directories::prune_stale_outputs(args.fs.as_ref(), &previous_outputs, &expected_outputs);
+        directories::write_manifest(args.fs.as_ref(), &output_root, &expected_outputs);
+    }
 }
@@ -1,11 +1,35 @@
 use std::collections::VecDeque;
 
-pub type ReplacementCount = usize;
+use crate::Error;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReplacementCount {
+    pub applied: usize,
+    pub defaulted: usize,
+    pub missing: usize,
+}
+
+impl ReplacementCount {
+    pub fn total(&self) -> usize {
+        self.applied + self.defaulted + self.missing
+    }
+}
+
+impl std::fmt::Display for ReplacementCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} applied, {} defaulted, {} missing",
+            self.applied, self.defaulted, self.missing
+        )
+    }
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Token<'a> {
     String(&'a str),
-    Replacement(&'a str),
+    // key, and an inline `%%key:default%%` fallback if one was given.
+    Replacement(&'a str, Option<&'a str>),
 }
 
 #[derive(Clone, Debug)]
@@ -24,33 +48,55 @@ impl<'a> Template<'a> {
         }
     }
 
-    pub fn apply(&self, replacement_map: &yaml_rust::Yaml) -> (ReplacementCount, String) {
+    /// Applies `replacement_map` to the template. In `strict` mode, a
+    /// replacement that resolves to neither a YAML string nor an inline
+    /// default is a hard error naming `file` and the missing key; otherwise
+    /// it's left as the literal `%%key%%` delimiters.
+    pub fn apply(
+        &self,
+        replacement_map: &yaml_rust::Yaml,
+        strict: bool,
+        file: &str,
+    ) -> Result<(ReplacementCount, String), Error> {
         let mut ret = String::new();
-        let mut count = 0;
+        let mut count = ReplacementCount::default();
 
         for tok in self.clone() {
             match tok {
                 Token::String(s) => ret.push_str(s),
-                Token::Replacement(s) => {
-                    let parts = s.split(".");
+                Token::Replacement(key, default) => {
                     let mut map = replacement_map;
-
-                    for part in parts {
+                    for part in key.split(".") {
                         map = &map[part];
                     }
 
-                    let replacement = match map.as_str() {
-                        Some(s) => s,
-                        None => &format!("{}{s}{}", self.open, self.close),
-                    };
-
-                    ret.push_str(replacement);
-                    count += 1;
+                    match map.as_str() {
+                        Some(s) => {
+                            ret.push_str(s);
+                            count.applied += 1;
+                        }
+                        None => match default {
+                            Some(default) => {
+                                ret.push_str(default);
+                                count.defaulted += 1;
+                            }
+                            None if strict => {
+                                return Err(Error::StrictReplacementError {
+                                    file: file.to_string(),
+                                    key: key.to_string(),
+                                });
+                            }
+                            None => {
+                                ret.push_str(&format!("{}{key}{}", self.open, self.close));
+                                count.missing += 1;
+                            }
+                        },
+                    }
                 }
             }
         }
 
-        (count, ret)
+        Ok((count, ret))
     }
 }
 
@@ -61,6 +107,13 @@ impl<'a> Iterator for Template<'a> {
     }
 }
 
+fn parse_replacement(spec: &str) -> (&str, Option<&str>) {
+    match spec.split_once(':') {
+        Some((key, default)) => (key, Some(default)),
+        None => (spec, None),
+    }
+}
+
 fn string_to_toks<'a>(mut s: &'a str, open: &str, close: &str) -> Vec<Token<'a>> {
     let mut ret = Vec::new();
     let mut inside = false;
@@ -78,7 +131,10 @@ fn string_to_toks<'a>(mut s: &'a str, open: &str, close: &str) -> Vec<Token<'a>>
 
                 if before_token.len() > 0 {
                     ret.push(match inside {
-                        true => Token::Replacement(before_token),
+                        true => {
+                            let (key, default) = parse_replacement(before_token);
+                            Token::Replacement(key, default)
+                        }
                         false => Token::String(before_token),
                     });
                 }
@@ -102,9 +158,9 @@ fn str_to_template() {
     let template = Template::from_str(text, "%%", "%%");
 
     assert_eq!(template.tokens.iter().count(), 4);
-    assert_eq!(template.tokens[0], Token::Replacement("name"));
+    assert_eq!(template.tokens[0], Token::Replacement("name", None));
     assert_eq!(template.tokens[1], Token::String(" Hello I am the "));
-    assert_eq!(template.tokens[2], Token::Replacement("name2"));
+    assert_eq!(template.tokens[2], Token::Replacement("name2", None));
     assert_eq!(template.tokens[3], Token::String(", pleased to meet you"));
 }
 
@@ -114,13 +170,11 @@ fn different_delims() {
     let template = Template::from_str(text, "xx0%", "abc%");
 
     assert_eq!(template.tokens.iter().count(), 2);
-    assert_eq!(template.tokens[0], Token::Replacement("name"));
+    assert_eq!(template.tokens[0], Token::Replacement("name", None));
     assert_eq!(
         template.tokens[1],
         Token::String(" Hello I am the %%name2%%, pleased to meet you")
     );
-
-    assert_eq!(template.data, text);
 }
 
 #[test]
@@ -132,10 +186,32 @@ fn apply_template() {
         yaml_rust::YamlLoader::load_from_str("names:\n  name: Test Name\n  name2: Test Name 2")
             .unwrap()[0]
             .clone();
-    let replaced = template.apply(&yaml);
+    let (count, replaced) = template.apply(&yaml, false, "test.txt").unwrap();
 
     assert_eq!(
         replaced,
         "Test Name Hello I am the Test Name 2, pleased to meet you %%names.invalid%%"
     );
+    assert_eq!(count.applied, 2);
+    assert_eq!(count.missing, 1);
+}
+
+#[test]
+fn apply_template_with_default() {
+    let text = "Hello %%name:World%%";
+    let template = Template::from_str(text, "%%", "%%");
+    let yaml = yaml_rust::Yaml::Null;
+    let (count, replaced) = template.apply(&yaml, false, "test.txt").unwrap();
+
+    assert_eq!(replaced, "Hello World");
+    assert_eq!(count.defaulted, 1);
+}
+
+#[test]
+fn apply_template_strict_errors_on_missing_key() {
+    let text = "Hello %%name%%";
+    let template = Template::from_str(text, "%%", "%%");
+    let yaml = yaml_rust::Yaml::Null;
+
+    assert!(template.apply(&yaml, true, "test.txt").is_err());
 }